@@ -1,5 +1,7 @@
 use crate::renderer::html::attribute::Attribute;
+use alloc::collections::VecDeque;
 use alloc::string::String;
+use alloc::string::ToString;
 use alloc::vec::Vec;
 
 #[derive(Debug, Clone, PartialEq, Eq)]
@@ -12,8 +14,37 @@ pub enum HtmlToken {
     EndTag {
         tag: String,
     },
+    Comment(String),
+    Doctype {
+        name: String,
+    },
     Char(char),
     Eof,
+    Error(ParseError),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ParseErrorKind {
+    UnexpectedNullCharacter,
+    EofInTag,
+    MissingSemicolonAfterCharacterReference,
+    InvalidFirstCharacterOfTagName,
+}
+
+// A recoverable tokenization problem, reported out of band (interleaved
+// into the token stream as `HtmlToken::Error`) instead of aborting, per
+// https://html.spec.whatwg.org/multipage/parsing.html#parse-errors.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ParseError {
+    pub kind: ParseErrorKind,
+    // 0-based index into the tokenizer's input at which the error occurred.
+    pub pos: usize,
+}
+
+impl ParseError {
+    fn new(kind: ParseErrorKind, pos: usize) -> Self {
+        Self { kind, pos }
+    }
 }
 
 #[derive(Debug, Clone, PartialEq, Eq)]
@@ -31,13 +62,230 @@ pub enum State {
     AttributeValueUnquoted,
     AfterAttributeValueQuoted,
     SelfClosingStartTag,
-    ScriptData,
-    ScriptDataLessThanSign,
-    ScriptDataEndTagOpen,
-    ScriptDataEndTagName,
+    RawText,
+    RawTextLessThanSign,
+    RawTextEndTagOpen,
+    RawTextEndTagName,
     TemporaryBuffer,
+    BogusComment,
+    CharacterReference,
+    NamedCharacterReference,
+    NumericCharacterReference,
+    NumericCharacterReferenceValue,
+    MarkupDeclarationOpen,
+    CommentStart,
+    Comment,
+    CommentEndDash,
+    CommentEnd,
+    Doctype,
+    BeforeDoctypeName,
+    DoctypeName,
+    AfterDoctypeName,
+}
+
+// A small subset of the named character references defined by the WHATWG
+// HTML spec (https://html.spec.whatwg.org/multipage/named-characters.html),
+// covering the entities web content actually relies on day to day.
+const NAMED_ENTITIES: &[(&str, &str)] = &[
+    ("amp;", "&"),
+    ("AMP;", "&"),
+    ("lt;", "<"),
+    ("LT;", "<"),
+    ("gt;", ">"),
+    ("GT;", ">"),
+    ("quot;", "\""),
+    ("QUOT;", "\""),
+    ("apos;", "'"),
+    ("nbsp;", "\u{00A0}"),
+    ("copy;", "\u{00A9}"),
+    ("COPY;", "\u{00A9}"),
+    ("reg;", "\u{00AE}"),
+    ("REG;", "\u{00AE}"),
+    ("trade;", "\u{2122}"),
+    ("hellip;", "\u{2026}"),
+    ("mdash;", "\u{2014}"),
+    ("ndash;", "\u{2013}"),
+    ("lsquo;", "\u{2018}"),
+    ("rsquo;", "\u{2019}"),
+    ("ldquo;", "\u{201C}"),
+    ("rdquo;", "\u{201D}"),
+    ("deg;", "\u{00B0}"),
+    ("plusmn;", "\u{00B1}"),
+    ("times;", "\u{00D7}"),
+    ("divide;", "\u{00F7}"),
+    ("sect;", "\u{00A7}"),
+    ("para;", "\u{00B6}"),
+    ("middot;", "\u{00B7}"),
+    ("laquo;", "\u{00AB}"),
+    ("raquo;", "\u{00BB}"),
+    ("cent;", "\u{00A2}"),
+    ("pound;", "\u{00A3}"),
+    ("yen;", "\u{00A5}"),
+    ("euro;", "\u{20AC}"),
+    ("micro;", "\u{00B5}"),
+    ("sup1;", "\u{00B9}"),
+    ("sup2;", "\u{00B2}"),
+    ("sup3;", "\u{00B3}"),
+    ("frac12;", "\u{00BD}"),
+    ("frac14;", "\u{00BC}"),
+    ("frac34;", "\u{00BE}"),
+];
+
+// Entities HTML4 allowed without a trailing `;`; HTML5 keeps accepting them
+// for backward compatibility (but only outside of this exact set).
+const LEGACY_NAMED_ENTITIES_WITHOUT_SEMICOLON: &[&str] = &[
+    "amp", "AMP", "lt", "LT", "gt", "GT", "quot", "QUOT", "nbsp", "copy", "COPY", "reg", "REG",
+];
+
+fn lookup_named_entity(name: &str) -> Option<&'static str> {
+    NAMED_ENTITIES
+        .iter()
+        .find(|(k, _)| *k == name)
+        .map(|(_, v)| *v)
+}
+
+fn is_legacy_named_entity(name: &str) -> bool {
+    LEGACY_NAMED_ENTITIES_WITHOUT_SEMICOLON.contains(&name)
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Encoding {
+    Utf8,
+    Utf16Le,
+    Utf16Be,
+    Windows1252,
+}
+
+// The Windows-1252 characters that differ from Latin-1 in the 0x80-0x9F
+// range (Latin-1 leaves those as C1 control codes). Browsers treat an
+// `iso-8859-1` declaration the same way, so we do too.
+const WINDOWS_1252_HIGH: [char; 32] = [
+    '\u{20AC}', '\u{0081}', '\u{201A}', '\u{0192}', '\u{201E}', '\u{2026}', '\u{2020}', '\u{2021}',
+    '\u{02C6}', '\u{2030}', '\u{0160}', '\u{2039}', '\u{0152}', '\u{008D}', '\u{017D}', '\u{008F}',
+    '\u{0090}', '\u{2018}', '\u{2019}', '\u{201C}', '\u{201D}', '\u{2022}', '\u{2013}', '\u{2014}',
+    '\u{02DC}', '\u{2122}', '\u{0161}', '\u{203A}', '\u{0153}', '\u{009D}', '\u{017E}', '\u{0178}',
+];
+
+// A code point past the valid Unicode range; a numeric character
+// reference's accumulator is clamped here instead of overflowing, and
+// resolves to U+FFFD once it reaches this.
+const CHAR_REF_CODE_OVERFLOW: u32 = 0x0011_0000;
+
+fn decode_windows_1252(bytes: &[u8]) -> String {
+    let mut s = String::with_capacity(bytes.len());
+    for &b in bytes {
+        let c = match b {
+            0x80..=0x9F => WINDOWS_1252_HIGH[(b - 0x80) as usize],
+            _ => b as char,
+        };
+        s.push(c);
+    }
+    s
+}
+
+fn decode_utf16(bytes: &[u8], little_endian: bool) -> String {
+    let mut units = Vec::with_capacity(bytes.len() / 2);
+    let mut i = 0;
+    while i + 1 < bytes.len() {
+        let unit = if little_endian {
+            u16::from_le_bytes([bytes[i], bytes[i + 1]])
+        } else {
+            u16::from_be_bytes([bytes[i], bytes[i + 1]])
+        };
+        units.push(unit);
+        i += 2;
+    }
+    char::decode_utf16(units)
+        .map(|r| r.unwrap_or('\u{FFFD}'))
+        .collect()
+}
+
+fn encoding_from_name(name: &[u8]) -> Option<Encoding> {
+    if name.len() > 32 {
+        return None;
+    }
+    let mut lower = [0u8; 32];
+    for (i, b) in name.iter().enumerate() {
+        lower[i] = b.to_ascii_lowercase();
+    }
+    match &lower[..name.len()] {
+        b"utf-8" | b"utf8" => Some(Encoding::Utf8),
+        b"utf-16" | b"utf-16le" => Some(Encoding::Utf16Le),
+        b"utf-16be" => Some(Encoding::Utf16Be),
+        b"windows-1252" | b"cp1252" | b"iso-8859-1" | b"latin1" => Some(Encoding::Windows1252),
+        _ => None,
+    }
+}
+
+// Scans (at most) the first 1024 bytes for a `<meta charset="...">` or
+// `<meta http-equiv="Content-Type" content="...charset=...">` declaration,
+// per https://html.spec.whatwg.org/multipage/parsing.html#prescan-a-byte-stream-to-determine-its-encoding.
+fn sniff_meta_charset(bytes: &[u8]) -> Option<Encoding> {
+    let window = &bytes[..bytes.len().min(1024)];
+    let needle = b"charset";
+    let mut i = 0;
+    while i + needle.len() <= window.len() {
+        if window[i..i + needle.len()].eq_ignore_ascii_case(needle) {
+            let mut j = i + needle.len();
+            while j < window.len() && window[j] == b' ' {
+                j += 1;
+            }
+            if j < window.len() && window[j] == b'=' {
+                j += 1;
+                while j < window.len() && window[j] == b' ' {
+                    j += 1;
+                }
+                if j < window.len() && (window[j] == b'"' || window[j] == b'\'') {
+                    j += 1;
+                }
+                let start = j;
+                while j < window.len()
+                    && !matches!(window[j], b'"' | b'\'' | b' ' | b'>' | b';')
+                {
+                    j += 1;
+                }
+                if let Some(encoding) = encoding_from_name(&window[start..j]) {
+                    return Some(encoding);
+                }
+            }
+        }
+        i += 1;
+    }
+    None
 }
 
+// Honors a leading BOM, then falls back to scanning for a declared
+// `charset`, and finally to UTF-8 — the algorithm the WHATWG spec calls
+// "encoding sniffing". Returns the detected encoding and how many leading
+// bytes (if any) were the BOM itself.
+fn sniff_encoding(bytes: &[u8]) -> (Encoding, usize) {
+    if bytes.starts_with(&[0xEF, 0xBB, 0xBF]) {
+        return (Encoding::Utf8, 3);
+    }
+    if bytes.starts_with(&[0xFF, 0xFE]) {
+        return (Encoding::Utf16Le, 2);
+    }
+    if bytes.starts_with(&[0xFE, 0xFF]) {
+        return (Encoding::Utf16Be, 2);
+    }
+    if let Some(encoding) = sniff_meta_charset(bytes) {
+        return (encoding, 0);
+    }
+    (Encoding::Utf8, 0)
+}
+
+// The default cap on how much undecided input `feed` will buffer before
+// reporting `MaxBufferError`; comfortably larger than any real tag, comment,
+// or character reference, while still bounding a response that never closes
+// one.
+const DEFAULT_MAX_BUFFER_SIZE: usize = 1_048_576;
+
+// Reported by `feed` when an unterminated construct (a tag, comment, etc.
+// that never closes) would otherwise make the buffered input grow without
+// bound.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MaxBufferError;
+
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub struct HtmlTokenizer {
     state: State,
@@ -46,6 +294,37 @@ pub struct HtmlTokenizer {
     latest_token: Option<HtmlToken>,
     input: Vec<char>,
     buf: String,
+    // The state to resume once the current detour is done with: a
+    // character reference has been resolved (`Data`, or one of the
+    // attribute-value states when `&` appears inside `attr="..."`), or a
+    // buffered literal in `TemporaryBuffer` has fully drained.
+    return_state: State,
+    char_ref_code: u32,
+    char_ref_is_hex: bool,
+    // Tokens produced while resolving a character reference, drained before
+    // the state machine advances any further.
+    pending_tokens: VecDeque<HtmlToken>,
+    // The tag name `RawText` is watching for in its matching end tag, e.g.
+    // "script" or "textarea".
+    current_raw_text_tag: String,
+    // Whether the element being read as raw text is RCDATA (character
+    // references decode, e.g. `textarea`/`title`) rather than RAWTEXT
+    // (literal, e.g. `script`/`style`).
+    is_rcdata: bool,
+    // Whether the caller has told us (via `end_of_input`) that no further
+    // chunks will arrive. `new`/`from_bytes` start with the whole document
+    // in hand, so they set this immediately; `new_streaming` leaves it
+    // unset until `end_of_input` is called.
+    input_ended: bool,
+    // How large `input` is allowed to grow while `feed`-ing; guards against
+    // a single unterminated tag/comment/character reference buffering an
+    // unbounded amount of a streamed response.
+    max_buffer_size: usize,
+    buffer_error: Option<MaxBufferError>,
+    // Whether the `EofInTag` parse error has already been reported for the
+    // tag currently stuck at true end of input; guards against reporting it
+    // again on every subsequent `next` call once input is exhausted.
+    eof_emitted: bool,
 }
 
 impl HtmlTokenizer {
@@ -57,11 +336,97 @@ impl HtmlTokenizer {
             latest_token: None,
             input: html.chars().collect(),
             buf: String::new(),
+            return_state: State::Data,
+            char_ref_code: 0,
+            char_ref_is_hex: false,
+            pending_tokens: VecDeque::new(),
+            current_raw_text_tag: String::new(),
+            is_rcdata: false,
+            input_ended: true,
+            max_buffer_size: DEFAULT_MAX_BUFFER_SIZE,
+            buffer_error: None,
+            eof_emitted: false,
         }
     }
 
-    fn is_eof(&self) -> bool {
-        self.pos > self.input.len()
+    // Builds a tokenizer directly from the raw bytes of an HTTP response,
+    // sniffing the encoding instead of requiring the caller to have
+    // decoded it already.
+    pub fn from_bytes(bytes: &[u8]) -> Self {
+        let (encoding, bom_len) = sniff_encoding(bytes);
+        let content = &bytes[bom_len..];
+        let decoded = match encoding {
+            Encoding::Utf8 => String::from_utf8_lossy(content).into_owned(),
+            Encoding::Utf16Le => decode_utf16(content, true),
+            Encoding::Utf16Be => decode_utf16(content, false),
+            Encoding::Windows1252 => decode_windows_1252(content),
+        };
+        Self::new(decoded)
+    }
+
+    // Builds a tokenizer for push-style, incremental input: feed chunks as
+    // they arrive over the network with `feed`, draining tokens with `next`
+    // between chunks, and call `end_of_input` once the response body is
+    // fully received.
+    pub fn new_streaming() -> Self {
+        let mut tokenizer = Self::new(String::new());
+        tokenizer.input_ended = false;
+        tokenizer
+    }
+
+    // Appends a chunk of already-decoded text. The portion of `input`
+    // already consumed (or only kept around for a possible reconsume) is
+    // dropped first, so memory use tracks the in-progress token rather than
+    // the whole document seen so far. Once `max_buffer_size` has been
+    // exceeded this stops accepting input; see `take_buffer_error`.
+    pub fn feed(&mut self, chunk: &str) {
+        if self.buffer_error.is_some() {
+            return;
+        }
+        self.compact();
+        for c in chunk.chars() {
+            if self.input.len() >= self.max_buffer_size {
+                self.buffer_error = Some(MaxBufferError);
+                return;
+            }
+            self.input.push(c);
+        }
+    }
+
+    // Signals that no further chunks will arrive. Once the buffered input
+    // is fully drained, `next` stops waiting for more and simply returns
+    // `None`.
+    pub fn end_of_input(&mut self) {
+        self.input_ended = true;
+    }
+
+    // Whether a subsequent `next` call can only ever return `None`: the
+    // buffered input is exhausted and the caller has said no more is
+    // coming.
+    pub fn is_finished(&self) -> bool {
+        self.input_ended && !self.reconsume && self.pos >= self.input.len()
+    }
+
+    // Returns (and clears) the error set by `feed` once an unterminated
+    // construct has pushed the buffered input past `max_buffer_size`.
+    pub fn take_buffer_error(&mut self) -> Option<MaxBufferError> {
+        self.buffer_error.take()
+    }
+
+    pub fn with_max_buffer_size(mut self, max_buffer_size: usize) -> Self {
+        self.max_buffer_size = max_buffer_size;
+        self
+    }
+
+    // Drops the prefix of `input` that's already been consumed (beyond the
+    // one character `reconsume_input` might still need), so a long-running
+    // streaming session doesn't retain the whole document in memory.
+    fn compact(&mut self) {
+        let keep_from = self.pos.saturating_sub(1);
+        if keep_from > 0 {
+            self.input.drain(0..keep_from);
+            self.pos -= keep_from;
+        }
     }
 
     fn consume_next_input(&mut self) -> char {
@@ -87,6 +452,12 @@ impl HtmlTokenizer {
         }
     }
 
+    // The `assert!`/`panic!` calls below guard this struct's own internal
+    // invariant (a tag or attribute is under construction whenever these are
+    // called) rather than anything about the input being tokenized; they
+    // can only fire from a bug in this state machine's transitions, not from
+    // malformed markup, which is reported through `HtmlToken::Error` /
+    // `ParseError` instead.
     fn append_tag_name(&mut self, c: char) {
         assert!(self.latest_token.is_some());
         if let Some(t) = self.latest_token.as_mut() {
@@ -157,29 +528,259 @@ impl HtmlTokenizer {
             }
         }
     }
+
+    // Whether `state` accumulates characters into an attribute value rather
+    // than the top-level token stream; determines how a resolved character
+    // reference should be delivered.
+    fn is_attribute_value_state(state: &State) -> bool {
+        matches!(
+            state,
+            State::AttributeValueDoubleQuoted
+                | State::AttributeValueSingleQuoted
+                | State::AttributeValueUnquoted
+        )
+    }
+
+    // Whether `state` is in the middle of building a tag, so running out of
+    // input here is specifically an `EofInTag` parse error rather than a
+    // silent stop.
+    fn is_tag_construction_state(state: &State) -> bool {
+        matches!(
+            state,
+            State::TagOpen
+                | State::EndTagOpen
+                | State::TagName
+                | State::BeforeAttributeName
+                | State::AttributeName
+                | State::AfterAttributeName
+                | State::BeforeAttributeValue
+                | State::AttributeValueDoubleQuoted
+                | State::AttributeValueSingleQuoted
+                | State::AttributeValueUnquoted
+                | State::AfterAttributeValueQuoted
+                | State::SelfClosingStartTag
+        )
+    }
+
+    // Accumulates one more digit into `char_ref_code`, saturating instead of
+    // overflowing so a reference like `&#4294967297;` can't panic. Clamped
+    // at `CHAR_REF_CODE_OVERFLOW` rather than `u32::MAX` so it stays there
+    // through further digits instead of needing to re-check on every one.
+    fn accumulate_char_ref_digit(&mut self, radix: u32, digit: u32) {
+        self.char_ref_code = self
+            .char_ref_code
+            .saturating_mul(radix)
+            .saturating_add(digit)
+            .min(CHAR_REF_CODE_OVERFLOW);
+    }
+
+    // Resolves the accumulated numeric character reference to the character
+    // it denotes. WHATWG's numeric character reference table remaps the C1
+    // range (0x80-0x9F) to Windows-1252's punctuation/currency characters
+    // instead of leaving them as control codes — the same mapping
+    // `decode_windows_1252` uses — ahead of the general `char::from_u32`
+    // case; null and out-of-range values resolve to U+FFFD.
+    fn resolve_char_ref_code(&self) -> char {
+        if self.char_ref_code == 0 || self.char_ref_code >= CHAR_REF_CODE_OVERFLOW {
+            return '\u{FFFD}';
+        }
+        if (0x80..=0x9F).contains(&self.char_ref_code) {
+            return WINDOWS_1252_HIGH[(self.char_ref_code - 0x80) as usize];
+        }
+        char::from_u32(self.char_ref_code).unwrap_or('\u{FFFD}')
+    }
+
+    // Resolves the buffered `&name` as a legacy named reference (without a
+    // trailing `;`) if one matches, falling back to emitting the buffer
+    // verbatim as an ambiguous ampersand otherwise. Shared by the
+    // `NamedCharacterReference` state's non-`;` fallthrough and its
+    // true-EOF handling in `Iterator::next`.
+    fn flush_legacy_or_literal_named_reference(&mut self) {
+        let name = self.buf[1..].to_string();
+        if is_legacy_named_entity(&name) {
+            if let Some(value) = lookup_named_entity(&(name + ";")) {
+                self.flush_char_ref(value);
+            }
+        } else {
+            let literal = self.buf.clone();
+            self.flush_char_ref(&literal);
+        }
+    }
+
+    // Delivers the text resolved from a character reference (or the raw
+    // literal when no reference matched) to wherever `return_state` expects
+    // its characters: appended to the in-progress attribute value, or queued
+    // as `Char` tokens to drain before the state machine resumes.
+    fn flush_char_ref(&mut self, text: &str) {
+        if Self::is_attribute_value_state(&self.return_state) {
+            for c in text.chars() {
+                self.append_attribute(c, false);
+            }
+        } else {
+            for c in text.chars() {
+                self.pending_tokens.push_back(HtmlToken::Char(c));
+            }
+        }
+    }
+
+    // Surfaces whatever a state was mid-construction with when input ran
+    // out for good, instead of letting it vanish. Called exactly once per
+    // exhausted input stream, from the `Iterator::next` char-fetch branch.
+    fn flush_at_eof(&mut self) -> Option<HtmlToken> {
+        if Self::is_tag_construction_state(&self.state) {
+            return Some(HtmlToken::Error(ParseError::new(
+                ParseErrorKind::EofInTag,
+                self.pos,
+            )));
+        }
+        match self.state {
+            State::BogusComment
+            | State::CommentStart
+            | State::Comment
+            | State::CommentEndDash
+            | State::CommentEnd => {
+                self.state = State::Data;
+                Some(HtmlToken::Comment(self.buf.clone()))
+            }
+            State::Doctype | State::BeforeDoctypeName => {
+                self.state = State::Data;
+                Some(HtmlToken::Doctype {
+                    name: String::new(),
+                })
+            }
+            State::DoctypeName | State::AfterDoctypeName => {
+                self.state = State::Data;
+                Some(HtmlToken::Doctype {
+                    name: self.buf.clone(),
+                })
+            }
+            State::CharacterReference => {
+                let literal = self.buf.clone();
+                self.flush_char_ref(&literal);
+                self.state = self.return_state.clone();
+                None
+            }
+            State::NamedCharacterReference => {
+                self.flush_legacy_or_literal_named_reference();
+                self.state = self.return_state.clone();
+                None
+            }
+            State::NumericCharacterReference | State::NumericCharacterReferenceValue => {
+                self.pending_tokens.push_back(HtmlToken::Error(ParseError::new(
+                    ParseErrorKind::MissingSemicolonAfterCharacterReference,
+                    self.pos,
+                )));
+                let resolved = self.resolve_char_ref_code();
+                let mut s = String::new();
+                s.push(resolved);
+                self.flush_char_ref(&s);
+                self.state = self.return_state.clone();
+                None
+            }
+            _ => None,
+        }
+    }
+
+    // Closes the start tag currently being built, switching into raw-text
+    // mode if its name is one of the elements whose content HTML defines as
+    // RAWTEXT or RCDATA rather than ordinary markup.
+    fn close_start_tag(&mut self) -> Option<HtmlToken> {
+        let raw_text_tag = match &self.latest_token {
+            Some(HtmlToken::StartTag { tag, .. }) => match tag.as_str() {
+                "script" | "style" => Some((tag.clone(), false)),
+                "textarea" | "title" => Some((tag.clone(), true)),
+                _ => None,
+            },
+            _ => None,
+        };
+        match raw_text_tag {
+            Some((tag, is_rcdata)) => {
+                self.current_raw_text_tag = tag;
+                self.is_rcdata = is_rcdata;
+                self.state = State::RawText;
+            }
+            None => self.state = State::Data,
+        }
+        self.take_latest_token()
+    }
+
+    fn enter_character_reference(&mut self, return_state: State) {
+        self.return_state = return_state;
+        self.buf = String::from("&");
+        self.state = State::CharacterReference;
+    }
+
+    // Looks ahead from `start` without consuming, so `MarkupDeclarationOpen`
+    // can tell `<!--` and `<!DOCTYPE` apart after only peeking at `!`.
+    fn matches_at(&self, start: usize, s: &str) -> bool {
+        let chars: Vec<char> = s.chars().collect();
+        if start + chars.len() > self.input.len() {
+            return false;
+        }
+        self.input[start..start + chars.len()] == chars[..]
+    }
+
+    fn matches_at_ignore_case(&self, start: usize, s: &str) -> bool {
+        let chars: Vec<char> = s.chars().collect();
+        if start + chars.len() > self.input.len() {
+            return false;
+        }
+        self.input[start..start + chars.len()]
+            .iter()
+            .zip(chars.iter())
+            .all(|(a, b)| a.eq_ignore_ascii_case(b))
+    }
 }
 
 impl Iterator for HtmlTokenizer {
     type Item = HtmlToken;
     fn next(&mut self) -> Option<Self::Item> {
-        if self.pos >= self.input.len() {
-            return None;
+        if let Some(t) = self.pending_tokens.pop_front() {
+            return Some(t);
         }
 
         loop {
-            let c = match self.reconsume {
-                true => self.reconsume_input(),
-                false => self.consume_next_input(),
+            if let Some(t) = self.pending_tokens.pop_front() {
+                return Some(t);
+            }
+
+            // Re-checked on every iteration (not just before the loop), so
+            // a chunk boundary landing mid-token just pauses here instead
+            // of running `consume_next_input` past the end of `input`;
+            // `feed` appends more and a later `next` call resumes cleanly.
+            let c = if self.reconsume {
+                self.reconsume_input()
+            } else if self.pos < self.input.len() {
+                self.consume_next_input()
+            } else if self.input_ended && !self.eof_emitted {
+                // Whatever state was mid-construction when input ran out
+                // still needs a chance to surface its buffered content (or
+                // an `EofInTag` error) instead of silently vanishing.
+                self.eof_emitted = true;
+                if let Some(t) = self.flush_at_eof() {
+                    return Some(t);
+                }
+                continue;
+            } else {
+                return None;
             };
 
             match self.state {
                 State::Data => {
+                    if c == '&' {
+                        self.enter_character_reference(State::Data);
+                        continue;
+                    }
                     if c == '<' {
                         self.state = State::TagOpen;
                         continue;
                     }
-                    if self.is_eof() {
-                        return Some(HtmlToken::Eof);
+                    if c == '\u{0000}' {
+                        self.pending_tokens.push_back(HtmlToken::Char('\u{FFFD}'));
+                        return Some(HtmlToken::Error(ParseError::new(
+                            ParseErrorKind::UnexpectedNullCharacter,
+                            self.pos - 1,
+                        )));
                     }
                     return Some(HtmlToken::Char(c));
                 }
@@ -188,22 +789,26 @@ impl Iterator for HtmlTokenizer {
                         self.state = State::EndTagOpen;
                         continue;
                     }
+                    if c == '!' {
+                        self.state = State::MarkupDeclarationOpen;
+                        continue;
+                    }
                     if c.is_ascii_alphabetic() {
                         self.reconsume = true;
                         self.state = State::TagName;
                         self.create_tag(true);
                         continue;
                     }
-                    if self.is_eof() {
-                        return Some(HtmlToken::Eof);
-                    }
+                    // e.g. `<1` or `< `: not a valid tag at all, just a
+                    // stray `<` followed by ordinary text.
+                    self.pending_tokens.push_back(HtmlToken::Error(ParseError::new(
+                        ParseErrorKind::InvalidFirstCharacterOfTagName,
+                        self.pos - 1,
+                    )));
                     self.reconsume = true;
                     self.state = State::Data;
                 }
                 State::EndTagOpen => {
-                    if self.is_eof() {
-                        return Some(HtmlToken::Eof);
-                    }
                     if c.is_ascii_alphabetic() {
                         self.reconsume = true;
                         self.state = State::TagName;
@@ -224,20 +829,16 @@ impl Iterator for HtmlTokenizer {
                     }
                     if c == '>' {
                         // <tag>
-                        self.state = State::Data;
-                        return self.take_latest_token();
+                        return self.close_start_tag();
                     }
                     if c.is_ascii_uppercase() {
                         self.append_tag_name(c.to_ascii_lowercase());
                         continue;
                     }
-                    if self.is_eof() {
-                        return Some(HtmlToken::Eof);
-                    }
                     self.append_tag_name(c);
                 }
                 State::BeforeAttributeName => {
-                    if c == '/' || c == '>' || self.is_eof() {
+                    if c == '/' || c == '>' {
                         self.reconsume = true;
                         self.state = State::AfterAttributeName;
                         continue;
@@ -247,7 +848,7 @@ impl Iterator for HtmlTokenizer {
                     self.start_new_attribute();
                 }
                 State::AttributeName => {
-                    if c == ' ' || c == '/' || c == '>' || self.is_eof() {
+                    if c == ' ' || c == '/' || c == '>' {
                         // <tag disabled>
                         self.reconsume = true;
                         self.state = State::AfterAttributeName;
@@ -279,11 +880,7 @@ impl Iterator for HtmlTokenizer {
                     }
                     if c == '>' {
                         // <tag attr >
-                        self.state = State::Data;
-                        return self.take_latest_token();
-                    }
-                    if self.is_eof() {
-                        return Some(HtmlToken::Eof);
+                        return self.close_start_tag();
                     }
                     // <tag attr1="value" a
                     self.reconsume = true;
@@ -309,36 +906,38 @@ impl Iterator for HtmlTokenizer {
                     self.state = State::AttributeValueUnquoted;
                 }
                 State::AttributeValueDoubleQuoted => {
+                    if c == '&' {
+                        self.enter_character_reference(State::AttributeValueDoubleQuoted);
+                        continue;
+                    }
                     if c == '"' {
                         self.state = State::AfterAttributeValueQuoted;
                         continue;
                     }
-                    if self.is_eof() {
-                        return Some(HtmlToken::Eof);
-                    }
                     self.append_attribute(c, false);
                 }
                 State::AttributeValueSingleQuoted => {
+                    if c == '&' {
+                        self.enter_character_reference(State::AttributeValueSingleQuoted);
+                        continue;
+                    }
                     if c == '\'' {
                         self.state = State::AfterAttributeValueQuoted;
                         continue;
                     }
-                    if self.is_eof() {
-                        return Some(HtmlToken::Eof);
-                    }
                     self.append_attribute(c, false);
                 }
                 State::AttributeValueUnquoted => {
+                    if c == '&' {
+                        self.enter_character_reference(State::AttributeValueUnquoted);
+                        continue;
+                    }
                     if c == ' ' {
                         self.state = State::BeforeAttributeName;
                         continue;
                     }
                     if c == '>' {
-                        self.state = State::Data;
-                        return self.take_latest_token();
-                    }
-                    if self.is_eof() {
-                        return Some(HtmlToken::Eof);
+                        return self.close_start_tag();
                     }
                     self.append_attribute(c, false);
                 }
@@ -352,11 +951,7 @@ impl Iterator for HtmlTokenizer {
                         continue;
                     }
                     if c == '>' {
-                        self.state = State::Data;
-                        return self.take_latest_token();
-                    }
-                    if self.is_eof() {
-                        return Some(HtmlToken::Eof);
+                        return self.close_start_tag();
                     }
                     self.reconsume = true;
                     self.state = State::BeforeAttributeName;
@@ -364,64 +959,88 @@ impl Iterator for HtmlTokenizer {
                 State::SelfClosingStartTag => {
                     if c == '>' {
                         self.set_self_closing_flag();
-                        self.state = State::Data;
-                        return self.take_latest_token();
-                    }
-                    if self.is_eof() {
-                        return Some(HtmlToken::Eof);
+                        return self.close_start_tag();
                     }
                 }
-                State::ScriptData => {
+                // Serves both RAWTEXT (`script`, `style` — literal text,
+                // no character references) and RCDATA (`textarea`, `title`
+                // — literal text, but character references still decode),
+                // distinguished by `self.is_rcdata`.
+                State::RawText => {
+                    if c == '&' && self.is_rcdata {
+                        self.enter_character_reference(State::RawText);
+                        continue;
+                    }
                     if c == '<' {
-                        self.state = State::ScriptDataLessThanSign;
+                        self.state = State::RawTextLessThanSign;
                         continue;
                     }
-                    if self.is_eof() {
-                        return Some(HtmlToken::Eof);
+                    if c == '\u{0000}' {
+                        self.pending_tokens.push_back(HtmlToken::Char('\u{FFFD}'));
+                        return Some(HtmlToken::Error(ParseError::new(
+                            ParseErrorKind::UnexpectedNullCharacter,
+                            self.pos - 1,
+                        )));
                     }
                     return Some(HtmlToken::Char(c));
                 }
-                State::ScriptDataLessThanSign => {
+                State::RawTextLessThanSign => {
                     if c == '/' {
-                        // reset buffer on </script>
                         self.buf = String::new();
-                        self.state = State::ScriptDataEndTagOpen;
+                        self.state = State::RawTextEndTagOpen;
                         continue;
                     }
                     self.reconsume = true;
-                    self.state = State::ScriptData;
-                    return Some(HtmlToken::Char(c));
+                    self.state = State::RawText;
+                    return Some(HtmlToken::Char('<'));
                 }
-                State::ScriptDataEndTagOpen => {
+                State::RawTextEndTagOpen => {
                     if c.is_ascii_alphabetic() {
                         self.reconsume = true;
-                        self.state = State::ScriptDataEndTagName;
+                        self.state = State::RawTextEndTagName;
                         self.create_tag(false);
                         continue;
                     }
                     self.reconsume = true;
-                    self.state = State::ScriptData;
-                    return Some(HtmlToken::Char('<'));
+                    self.state = State::RawText;
+                    self.pending_tokens.push_back(HtmlToken::Char('<'));
+                    return Some(HtmlToken::Char('/'));
                 }
-                State::ScriptDataEndTagName => {
+                State::RawTextEndTagName => {
                     if c == '>' {
-                        self.state = State::Data;
-                        return self.take_latest_token();
+                        let is_appropriate_end_tag = matches!(
+                            &self.latest_token,
+                            Some(HtmlToken::EndTag { tag }) if tag == &self.current_raw_text_tag
+                        );
+                        if is_appropriate_end_tag {
+                            self.state = State::Data;
+                            return self.take_latest_token();
+                        }
+                        // Not the tag that opened this raw-text element
+                        // (e.g. `</b>` inside a `<script>`); the whole
+                        // thing is just text.
+                        self.latest_token = None;
+                        self.return_state = State::RawText;
+                        self.state = State::TemporaryBuffer;
+                        self.buf = String::from("</") + &self.buf + ">";
+                        continue;
                     }
                     if c.is_ascii_alphabetic() {
                         self.buf.push(c);
                         self.append_tag_name(c.to_ascii_lowercase());
                         continue;
                     }
+                    self.latest_token = None;
+                    self.return_state = State::RawText;
                     self.state = State::TemporaryBuffer;
-                    self.buf = String::from("<") + &self.buf;
+                    self.buf = String::from("</") + &self.buf;
                     self.buf.push(c);
                     continue;
                 }
                 State::TemporaryBuffer => {
                     self.reconsume = true;
                     if self.buf.chars().count() == 0 {
-                        self.state = State::ScriptData;
+                        self.state = self.return_state.clone();
                         continue;
                     }
                     // remove the first char
@@ -433,27 +1052,392 @@ impl Iterator for HtmlTokenizer {
                     self.buf.remove(0);
                     return Some(HtmlToken::Char(c));
                 }
+                State::CharacterReference => {
+                    if c.is_ascii_alphanumeric() {
+                        self.reconsume = true;
+                        self.state = State::NamedCharacterReference;
+                        continue;
+                    }
+                    if c == '#' {
+                        self.buf.push('#');
+                        self.char_ref_code = 0;
+                        self.char_ref_is_hex = false;
+                        self.state = State::NumericCharacterReference;
+                        continue;
+                    }
+                    // Not a character reference at all; put the `&` back
+                    // verbatim and resume the state we came from.
+                    self.reconsume = true;
+                    let literal = self.buf.clone();
+                    self.flush_char_ref(&literal);
+                    self.state = self.return_state.clone();
+                }
+                State::NamedCharacterReference => {
+                    if c.is_ascii_alphanumeric() {
+                        self.buf.push(c);
+                        continue;
+                    }
+                    if c == ';' {
+                        self.buf.push(';');
+                        let name = self.buf[1..].to_string();
+                        match lookup_named_entity(&name) {
+                            Some(value) => self.flush_char_ref(value),
+                            None => {
+                                let literal = self.buf.clone();
+                                self.flush_char_ref(&literal);
+                            }
+                        }
+                        self.state = self.return_state.clone();
+                        continue;
+                    }
+                    // Legacy named references (e.g. `&amp`) may omit the
+                    // trailing `;`; anything else is an ambiguous ampersand
+                    // and is emitted verbatim. But per the WHATWG "historical
+                    // reasons" rule, inside an attribute value a legacy match
+                    // followed by `=` or an alphanumeric must not be
+                    // resolved — otherwise unencoded query strings like
+                    // `?x&copy=1` would be corrupted.
+                    if Self::is_attribute_value_state(&self.return_state)
+                        && (c == '=' || c.is_ascii_alphanumeric())
+                    {
+                        let literal = self.buf.clone();
+                        self.flush_char_ref(&literal);
+                    } else {
+                        self.flush_legacy_or_literal_named_reference();
+                    }
+                    self.reconsume = true;
+                    self.state = self.return_state.clone();
+                }
+                State::NumericCharacterReference => {
+                    if c == 'x' || c == 'X' {
+                        self.buf.push(c);
+                        self.char_ref_is_hex = true;
+                        self.state = State::NumericCharacterReferenceValue;
+                        continue;
+                    }
+                    self.char_ref_is_hex = false;
+                    self.reconsume = true;
+                    self.state = State::NumericCharacterReferenceValue;
+                }
+                State::NumericCharacterReferenceValue => {
+                    if self.char_ref_is_hex && c.is_ascii_hexdigit() {
+                        self.accumulate_char_ref_digit(16, c.to_digit(16).unwrap_or(0));
+                        continue;
+                    }
+                    if !self.char_ref_is_hex && c.is_ascii_digit() {
+                        self.accumulate_char_ref_digit(10, c.to_digit(10).unwrap_or(0));
+                        continue;
+                    }
+                    // A missing trailing `;` is tolerated; we resolve what
+                    // we have and reconsume the terminating character, but
+                    // still flag it per the WHATWG spec.
+                    if c != ';' {
+                        self.reconsume = true;
+                        self.pending_tokens.push_back(HtmlToken::Error(ParseError::new(
+                            ParseErrorKind::MissingSemicolonAfterCharacterReference,
+                            self.pos,
+                        )));
+                    }
+                    let resolved = self.resolve_char_ref_code();
+                    let mut s = String::new();
+                    s.push(resolved);
+                    self.flush_char_ref(&s);
+                    self.state = self.return_state.clone();
+                }
+                State::MarkupDeclarationOpen => {
+                    // `c` is the char right after `!`; peek from there to
+                    // tell a comment, a DOCTYPE, and anything else apart.
+                    let start = self.pos - 1;
+                    if self.matches_at(start, "--") {
+                        self.pos = start + 2;
+                        self.buf = String::new();
+                        self.state = State::CommentStart;
+                        continue;
+                    }
+                    if self.matches_at_ignore_case(start, "DOCTYPE") {
+                        self.pos = start + 7;
+                        self.buf = String::new();
+                        self.state = State::Doctype;
+                        continue;
+                    }
+                    // Unsupported declaration (e.g. `<![if IE]>` or
+                    // `<!ENTITY ...>`); treat the rest as a bogus comment
+                    // instead of giving up on it.
+                    self.pos = start;
+                    self.buf = String::new();
+                    self.state = State::BogusComment;
+                    continue;
+                }
+                State::CommentStart => {
+                    if c == '-' {
+                        self.state = State::CommentEndDash;
+                        continue;
+                    }
+                    self.reconsume = true;
+                    self.state = State::Comment;
+                }
+                State::Comment => {
+                    if c == '-' {
+                        self.state = State::CommentEndDash;
+                        continue;
+                    }
+                    self.buf.push(c);
+                }
+                // An unsupported markup declaration (anything other than
+                // `<!--` or `<!DOCTYPE`) is tokenized as a comment, but
+                // unlike a real comment it ends on the next `>` rather than
+                // `-->` — https://html.spec.whatwg.org/multipage/parsing.html#bogus-comment-state.
+                State::BogusComment => {
+                    if c == '>' {
+                        self.state = State::Data;
+                        return Some(HtmlToken::Comment(self.buf.clone()));
+                    }
+                    self.buf.push(c);
+                }
+                State::CommentEndDash => {
+                    if c == '-' {
+                        self.state = State::CommentEnd;
+                        continue;
+                    }
+                    // A lone `-` not followed by another one is just part
+                    // of the comment data.
+                    self.buf.push('-');
+                    self.reconsume = true;
+                    self.state = State::Comment;
+                }
+                State::CommentEnd => {
+                    if c == '>' {
+                        self.state = State::Data;
+                        return Some(HtmlToken::Comment(self.buf.clone()));
+                    }
+                    if c == '-' {
+                        // e.g. `<!--foo--->`
+                        self.buf.push('-');
+                        continue;
+                    }
+                    self.buf.push('-');
+                    self.buf.push('-');
+                    self.reconsume = true;
+                    self.state = State::Comment;
+                }
+                State::Doctype => {
+                    if c == ' ' {
+                        self.state = State::BeforeDoctypeName;
+                        continue;
+                    }
+                    self.reconsume = true;
+                    self.state = State::BeforeDoctypeName;
+                }
+                State::BeforeDoctypeName => {
+                    if c == ' ' {
+                        continue;
+                    }
+                    if c == '>' {
+                        self.state = State::Data;
+                        return Some(HtmlToken::Doctype {
+                            name: String::new(),
+                        });
+                    }
+                    self.buf = String::new();
+                    self.reconsume = true;
+                    self.state = State::DoctypeName;
+                }
+                State::DoctypeName => {
+                    if c == '>' {
+                        self.state = State::Data;
+                        return Some(HtmlToken::Doctype {
+                            name: self.buf.clone(),
+                        });
+                    }
+                    if c == ' ' {
+                        self.state = State::AfterDoctypeName;
+                        continue;
+                    }
+                    if c.is_ascii_uppercase() {
+                        self.buf.push(c.to_ascii_lowercase());
+                        continue;
+                    }
+                    self.buf.push(c);
+                }
+                // A legacy PUBLIC/SYSTEM identifier (or anything else)
+                // between the name and `>` is discarded; only the name
+                // itself is kept.
+                State::AfterDoctypeName => {
+                    if c == '>' {
+                        self.state = State::Data;
+                        return Some(HtmlToken::Doctype {
+                            name: self.buf.clone(),
+                        });
+                    }
+                }
             }
         }
     }
 }
 
-#[cfg(test)]
-mod tests {
-    use super::*;
-    use alloc::string::ToString;
-    use alloc::vec;
+// Whether `tag` is one of the elements `html_to_text` breaks lines around.
+fn is_block_level_tag(tag: &str) -> bool {
+    matches!(
+        tag,
+        "p" | "div" | "li" | "br" | "h1" | "h2" | "h3" | "h4" | "h5" | "h6"
+    )
+}
 
-    #[test]
-    fn test_empty() {
-        let html = "".to_string();
-        let mut tokenizer = HtmlTokenizer::new(html);
-        assert!(tokenizer.next().is_none());
+// Renders `html` as plain text by draining `HtmlTokenizer`: character
+// references already decode to `Char` tokens, so this just concatenates
+// them, while dropping `script`/`style` contents and turning block-level
+// tags into line breaks so paragraphs and list items don't run together.
+// Useful for a text-only rendering surface, or for search/snippet
+// extraction.
+pub fn html_to_text(html: &str) -> String {
+    let tokenizer = HtmlTokenizer::new(html.to_string());
+    let mut text = String::new();
+    let mut suppressing: Option<String> = None;
+    for token in tokenizer {
+        match token {
+            HtmlToken::StartTag { tag, .. } => {
+                if tag == "script" || tag == "style" {
+                    suppressing = Some(tag);
+                    continue;
+                }
+                if is_block_level_tag(&tag) {
+                    text.push('\n');
+                }
+            }
+            HtmlToken::EndTag { tag } => {
+                if suppressing.as_deref() == Some(tag.as_str()) {
+                    suppressing = None;
+                    continue;
+                }
+                if is_block_level_tag(&tag) {
+                    text.push('\n');
+                }
+            }
+            HtmlToken::Char(c) => {
+                if suppressing.is_none() {
+                    text.push(c);
+                }
+            }
+            HtmlToken::Comment(_) | HtmlToken::Doctype { .. } | HtmlToken::Eof | HtmlToken::Error(_) => {}
+        }
     }
+    text
+}
 
-    #[test]
-    fn test_start_and_end_tag() {
-        let html = "<body></body>".to_string();
+// Elements the serializer never writes a closing tag for, matching the
+// WHATWG list of void elements
+// (https://html.spec.whatwg.org/multipage/syntax.html#void-elements).
+fn is_void_element(tag: &str) -> bool {
+    matches!(
+        tag,
+        "area"
+            | "base"
+            | "br"
+            | "col"
+            | "embed"
+            | "hr"
+            | "img"
+            | "input"
+            | "link"
+            | "meta"
+            | "param"
+            | "source"
+            | "track"
+            | "wbr"
+    )
+}
+
+// Escapes the characters that would otherwise be misread as markup inside
+// a double-quoted attribute value.
+fn escape_attribute_value(value: &str) -> String {
+    let mut escaped = String::with_capacity(value.len());
+    for c in value.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            _ => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+// Reconstructs HTML source from a token stream, the inverse of
+// `HtmlTokenizer`. Lets callers tokenize, transform (sanitizing, attribute
+// rewriting, URL rewriting), and re-emit markup instead of only consuming
+// tokens one way.
+pub fn serialize(tokens: impl Iterator<Item = HtmlToken>) -> String {
+    let mut html = String::new();
+    for token in tokens {
+        match token {
+            HtmlToken::StartTag {
+                tag,
+                self_closing,
+                attributes,
+            } => {
+                html.push('<');
+                html.push_str(&tag);
+                for attr in &attributes {
+                    html.push(' ');
+                    html.push_str(&attr.name());
+                    html.push_str("=\"");
+                    html.push_str(&escape_attribute_value(&attr.value()));
+                    html.push('"');
+                }
+                if self_closing {
+                    html.push_str(" />");
+                } else {
+                    html.push('>');
+                }
+            }
+            HtmlToken::EndTag { tag } => {
+                if !is_void_element(&tag) {
+                    html.push_str("</");
+                    html.push_str(&tag);
+                    html.push('>');
+                }
+            }
+            HtmlToken::Char(c) => match c {
+                '&' => html.push_str("&amp;"),
+                '<' => html.push_str("&lt;"),
+                '>' => html.push_str("&gt;"),
+                _ => html.push(c),
+            },
+            HtmlToken::Comment(text) => {
+                html.push_str("<!--");
+                html.push_str(&text);
+                html.push_str("-->");
+            }
+            HtmlToken::Doctype { name } => {
+                html.push_str("<!DOCTYPE");
+                if !name.is_empty() {
+                    html.push(' ');
+                    html.push_str(&name);
+                }
+                html.push('>');
+            }
+            HtmlToken::Eof | HtmlToken::Error(_) => {}
+        }
+    }
+    html
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use alloc::vec;
+
+    #[test]
+    fn test_empty() {
+        let html = "".to_string();
+        let mut tokenizer = HtmlTokenizer::new(html);
+        assert!(tokenizer.next().is_none());
+    }
+
+    #[test]
+    fn test_start_and_end_tag() {
+        let html = "<body></body>".to_string();
         let mut tokenizer = HtmlTokenizer::new(html);
         let expected = [
             HtmlToken::StartTag {
@@ -542,4 +1526,515 @@ mod tests {
             assert_eq!(Some(e), tokenizer.next());
         }
     }
+
+    #[test]
+    fn test_named_character_reference() {
+        let html = "&amp;&lt;&gt;&quot;&apos;&nbsp;".to_string();
+        let mut tokenizer = HtmlTokenizer::new(html);
+        let expected = [
+            HtmlToken::Char('&'),
+            HtmlToken::Char('<'),
+            HtmlToken::Char('>'),
+            HtmlToken::Char('"'),
+            HtmlToken::Char('\''),
+            HtmlToken::Char('\u{00A0}'),
+        ];
+        for e in expected {
+            assert_eq!(Some(e), tokenizer.next());
+        }
+    }
+
+    #[test]
+    fn test_numeric_character_reference() {
+        let html = "&#169;&#x1F600;".to_string();
+        let mut tokenizer = HtmlTokenizer::new(html);
+        let expected = [HtmlToken::Char('\u{00A9}'), HtmlToken::Char('\u{1F600}')];
+        for e in expected {
+            assert_eq!(Some(e), tokenizer.next());
+        }
+    }
+
+    #[test]
+    fn test_numeric_character_reference_overflow_does_not_panic() {
+        let html = "&#4294967297;".to_string();
+        let mut tokenizer = HtmlTokenizer::new(html);
+        assert_eq!(Some(HtmlToken::Char('\u{FFFD}')), tokenizer.next());
+    }
+
+    #[test]
+    fn test_numeric_character_reference_windows_1252_override() {
+        let html = "&#128;&#147;".to_string();
+        let mut tokenizer = HtmlTokenizer::new(html);
+        let expected = [HtmlToken::Char('\u{20AC}'), HtmlToken::Char('\u{201C}')];
+        for e in expected {
+            assert_eq!(Some(e), tokenizer.next());
+        }
+    }
+
+    #[test]
+    fn test_legacy_named_character_reference_without_semicolon() {
+        let html = "&amp b".to_string();
+        let mut tokenizer = HtmlTokenizer::new(html);
+        let expected = [
+            HtmlToken::Char('&'),
+            HtmlToken::Char(' '),
+            HtmlToken::Char('b'),
+        ];
+        for e in expected {
+            assert_eq!(Some(e), tokenizer.next());
+        }
+    }
+
+    #[test]
+    fn test_legacy_named_character_reference_flushed_at_eof() {
+        let html = "&amp".to_string();
+        let mut tokenizer = HtmlTokenizer::new(html);
+        assert_eq!(Some(HtmlToken::Char('&')), tokenizer.next());
+        assert!(tokenizer.next().is_none());
+    }
+
+    #[test]
+    fn test_ambiguous_ampersand_is_emitted_verbatim() {
+        let html = "&unknown;".to_string();
+        let mut tokenizer = HtmlTokenizer::new(html);
+        let expected: Vec<HtmlToken> = "&unknown;".chars().map(HtmlToken::Char).collect();
+        for e in expected {
+            assert_eq!(Some(e), tokenizer.next());
+        }
+    }
+
+    #[test]
+    fn test_character_reference_in_attribute_value() {
+        let html = "<a href=\"?a=1&amp;b=2\"></a>".to_string();
+        let mut tokenizer = HtmlTokenizer::new(html);
+
+        let mut attr = Attribute::new();
+        for c in "href".chars() {
+            attr.add_char(c, true);
+        }
+        for c in "?a=1&b=2".chars() {
+            attr.add_char(c, false);
+        }
+        let expected = [
+            HtmlToken::StartTag {
+                tag: "a".to_string(),
+                self_closing: false,
+                attributes: vec![attr],
+            },
+            HtmlToken::EndTag {
+                tag: "a".to_string(),
+            },
+        ];
+        for e in expected {
+            assert_eq!(Some(e), tokenizer.next());
+        }
+    }
+
+    #[test]
+    fn test_legacy_named_reference_not_resolved_before_equals_in_attribute() {
+        let html = "<a href=\"?x&copy=1\">".to_string();
+        let mut tokenizer = HtmlTokenizer::new(html);
+
+        let mut attr = Attribute::new();
+        for c in "href".chars() {
+            attr.add_char(c, true);
+        }
+        for c in "?x&copy=1".chars() {
+            attr.add_char(c, false);
+        }
+        assert_eq!(
+            Some(HtmlToken::StartTag {
+                tag: "a".to_string(),
+                self_closing: false,
+                attributes: vec![attr],
+            }),
+            tokenizer.next()
+        );
+    }
+
+    #[test]
+    fn test_comment() {
+        let html = "<!-- hello -->".to_string();
+        let mut tokenizer = HtmlTokenizer::new(html);
+        assert_eq!(
+            Some(HtmlToken::Comment(" hello ".to_string())),
+            tokenizer.next()
+        );
+    }
+
+    #[test]
+    fn test_comment_flushed_at_eof() {
+        let html = "<!-- foo".to_string();
+        let mut tokenizer = HtmlTokenizer::new(html);
+        assert_eq!(
+            Some(HtmlToken::Comment(" foo".to_string())),
+            tokenizer.next()
+        );
+        assert!(tokenizer.next().is_none());
+    }
+
+    #[test]
+    fn test_empty_comment_flushed_at_eof() {
+        let html = "<!--".to_string();
+        let mut tokenizer = HtmlTokenizer::new(html);
+        assert_eq!(
+            Some(HtmlToken::Comment(String::new())),
+            tokenizer.next()
+        );
+        assert!(tokenizer.next().is_none());
+    }
+
+    #[test]
+    fn test_bogus_comment_ends_on_closing_angle_bracket() {
+        let html = "<![if IE]>bar".to_string();
+        let mut tokenizer = HtmlTokenizer::new(html);
+        let expected = [
+            HtmlToken::Comment("[if IE]".to_string()),
+            HtmlToken::Char('b'),
+            HtmlToken::Char('a'),
+            HtmlToken::Char('r'),
+        ];
+        for e in expected {
+            assert_eq!(Some(e), tokenizer.next());
+        }
+    }
+
+    #[test]
+    fn test_bogus_comment_flushed_at_eof() {
+        let html = "<!foo".to_string();
+        let mut tokenizer = HtmlTokenizer::new(html);
+        assert_eq!(
+            Some(HtmlToken::Comment("foo".to_string())),
+            tokenizer.next()
+        );
+        assert!(tokenizer.next().is_none());
+    }
+
+    #[test]
+    fn test_doctype() {
+        let html = "<!DOCTYPE html>".to_string();
+        let mut tokenizer = HtmlTokenizer::new(html);
+        assert_eq!(
+            Some(HtmlToken::Doctype {
+                name: "html".to_string()
+            }),
+            tokenizer.next()
+        );
+    }
+
+    #[test]
+    fn test_doctype_flushed_at_eof() {
+        let html = "<!DOCTYPE html".to_string();
+        let mut tokenizer = HtmlTokenizer::new(html);
+        assert_eq!(
+            Some(HtmlToken::Doctype {
+                name: "html".to_string()
+            }),
+            tokenizer.next()
+        );
+        assert!(tokenizer.next().is_none());
+    }
+
+    #[test]
+    fn test_doctype_ignores_legacy_public_identifier() {
+        let html = "<!DOCTYPE html PUBLIC \"-//W3C//DTD\">".to_string();
+        let mut tokenizer = HtmlTokenizer::new(html);
+        assert_eq!(
+            Some(HtmlToken::Doctype {
+                name: "html".to_string()
+            }),
+            tokenizer.next()
+        );
+    }
+
+    #[test]
+    fn test_style_rawtext_with_angle_bracket() {
+        let html = "<style>a > b {}</style>".to_string();
+        let mut tokenizer = HtmlTokenizer::new(html);
+        let mut expected = Vec::new();
+        expected.push(HtmlToken::StartTag {
+            tag: "style".to_string(),
+            self_closing: false,
+            attributes: Vec::new(),
+        });
+        for c in "a > b {}".chars() {
+            expected.push(HtmlToken::Char(c));
+        }
+        expected.push(HtmlToken::EndTag {
+            tag: "style".to_string(),
+        });
+        for e in expected {
+            assert_eq!(Some(e), tokenizer.next());
+        }
+    }
+
+    #[test]
+    fn test_textarea_rcdata_decodes_character_references() {
+        let html = "<textarea>a &amp; b</textarea>".to_string();
+        let mut tokenizer = HtmlTokenizer::new(html);
+        let mut expected = Vec::new();
+        expected.push(HtmlToken::StartTag {
+            tag: "textarea".to_string(),
+            self_closing: false,
+            attributes: Vec::new(),
+        });
+        for c in "a & b".chars() {
+            expected.push(HtmlToken::Char(c));
+        }
+        expected.push(HtmlToken::EndTag {
+            tag: "textarea".to_string(),
+        });
+        for e in expected {
+            assert_eq!(Some(e), tokenizer.next());
+        }
+    }
+
+    #[test]
+    fn test_title_rawtext_ignores_mismatched_end_tag() {
+        let html = "<title>a </b> b</title>".to_string();
+        let mut tokenizer = HtmlTokenizer::new(html);
+        let mut expected = Vec::new();
+        expected.push(HtmlToken::StartTag {
+            tag: "title".to_string(),
+            self_closing: false,
+            attributes: Vec::new(),
+        });
+        for c in "a </b> b".chars() {
+            expected.push(HtmlToken::Char(c));
+        }
+        expected.push(HtmlToken::EndTag {
+            tag: "title".to_string(),
+        });
+        for e in expected {
+            assert_eq!(Some(e), tokenizer.next());
+        }
+    }
+
+    #[test]
+    fn test_from_bytes_utf8_bom() {
+        let mut bytes = vec![0xEF, 0xBB, 0xBF];
+        bytes.extend_from_slice("<p>hi</p>".as_bytes());
+        let mut tokenizer = HtmlTokenizer::from_bytes(&bytes);
+        assert_eq!(
+            Some(HtmlToken::StartTag {
+                tag: "p".to_string(),
+                self_closing: false,
+                attributes: Vec::new(),
+            }),
+            tokenizer.next()
+        );
+    }
+
+    #[test]
+    fn test_from_bytes_meta_charset() {
+        let html = "<meta charset=\"windows-1252\"><p>caf\u{e9}</p>".to_string();
+        let mut bytes: Vec<u8> = Vec::new();
+        for c in html.chars() {
+            bytes.push(c as u8);
+        }
+        let mut tokenizer = HtmlTokenizer::from_bytes(&bytes);
+        // <meta ...>
+        assert!(matches!(tokenizer.next(), Some(HtmlToken::StartTag { .. })));
+        // <p>
+        assert!(matches!(tokenizer.next(), Some(HtmlToken::StartTag { .. })));
+        for c in "caf\u{e9}".chars() {
+            assert_eq!(Some(HtmlToken::Char(c)), tokenizer.next());
+        }
+    }
+
+    #[test]
+    fn test_from_bytes_falls_back_to_utf8() {
+        let bytes = "<p>ok</p>".as_bytes();
+        let mut tokenizer = HtmlTokenizer::from_bytes(bytes);
+        assert!(matches!(tokenizer.next(), Some(HtmlToken::StartTag { .. })));
+    }
+
+    #[test]
+    fn test_feed_splits_tag_across_chunks() {
+        let mut tokenizer = HtmlTokenizer::new_streaming();
+        tokenizer.feed("<scr");
+        assert_eq!(tokenizer.next(), None);
+        tokenizer.feed("ipt>hi</scri");
+        assert_eq!(
+            tokenizer.next(),
+            Some(HtmlToken::StartTag {
+                tag: "script".to_string(),
+                self_closing: false,
+                attributes: Vec::new(),
+            })
+        );
+        assert_eq!(tokenizer.next(), Some(HtmlToken::Char('h')));
+        assert_eq!(tokenizer.next(), Some(HtmlToken::Char('i')));
+        assert_eq!(tokenizer.next(), None);
+        tokenizer.feed("pt>");
+        assert_eq!(
+            tokenizer.next(),
+            Some(HtmlToken::EndTag {
+                tag: "script".to_string(),
+            })
+        );
+        assert_eq!(tokenizer.next(), None);
+        assert!(!tokenizer.is_finished());
+        tokenizer.end_of_input();
+        assert!(tokenizer.is_finished());
+        assert_eq!(tokenizer.next(), None);
+    }
+
+    #[test]
+    fn test_feed_reports_max_buffer_error() {
+        let mut tokenizer = HtmlTokenizer::new_streaming().with_max_buffer_size(8);
+        tokenizer.feed("<p this-attr-name-never-ends");
+        assert_eq!(tokenizer.take_buffer_error(), Some(MaxBufferError));
+        assert_eq!(tokenizer.take_buffer_error(), None);
+    }
+
+    #[test]
+    fn test_unexpected_null_character_in_data() {
+        let html = "a\u{0000}b".to_string();
+        let mut tokenizer = HtmlTokenizer::new(html);
+        let expected = [
+            HtmlToken::Char('a'),
+            HtmlToken::Error(ParseError::new(ParseErrorKind::UnexpectedNullCharacter, 1)),
+            HtmlToken::Char('\u{FFFD}'),
+            HtmlToken::Char('b'),
+        ];
+        for e in expected {
+            assert_eq!(Some(e), tokenizer.next());
+        }
+    }
+
+    #[test]
+    fn test_invalid_first_character_of_tag_name() {
+        let html = "<1>".to_string();
+        let mut tokenizer = HtmlTokenizer::new(html);
+        let expected = [
+            HtmlToken::Error(ParseError::new(
+                ParseErrorKind::InvalidFirstCharacterOfTagName,
+                1,
+            )),
+            HtmlToken::Char('1'),
+            HtmlToken::Char('>'),
+        ];
+        for e in expected {
+            assert_eq!(Some(e), tokenizer.next());
+        }
+    }
+
+    #[test]
+    fn test_missing_semicolon_after_character_reference() {
+        let html = "&#65b".to_string();
+        let mut tokenizer = HtmlTokenizer::new(html);
+        let expected = [
+            HtmlToken::Error(ParseError::new(
+                ParseErrorKind::MissingSemicolonAfterCharacterReference,
+                5,
+            )),
+            HtmlToken::Char('A'),
+            HtmlToken::Char('b'),
+        ];
+        for e in expected {
+            assert_eq!(Some(e), tokenizer.next());
+        }
+    }
+
+    #[test]
+    fn test_bare_ampersand_flushed_at_eof() {
+        let html = "&".to_string();
+        let mut tokenizer = HtmlTokenizer::new(html);
+        assert_eq!(Some(HtmlToken::Char('&')), tokenizer.next());
+        assert!(tokenizer.next().is_none());
+    }
+
+    #[test]
+    fn test_trailing_ampersand_flushed_at_eof() {
+        let html = "x&".to_string();
+        let mut tokenizer = HtmlTokenizer::new(html);
+        let expected = [HtmlToken::Char('x'), HtmlToken::Char('&')];
+        for e in expected {
+            assert_eq!(Some(e), tokenizer.next());
+        }
+        assert!(tokenizer.next().is_none());
+    }
+
+    #[test]
+    fn test_numeric_character_reference_flushed_at_eof() {
+        let html = "&#65".to_string();
+        let mut tokenizer = HtmlTokenizer::new(html);
+        let expected = [
+            HtmlToken::Error(ParseError::new(
+                ParseErrorKind::MissingSemicolonAfterCharacterReference,
+                4,
+            )),
+            HtmlToken::Char('A'),
+        ];
+        for e in expected {
+            assert_eq!(Some(e), tokenizer.next());
+        }
+        assert!(tokenizer.next().is_none());
+    }
+
+    #[test]
+    fn test_hex_character_reference_flushed_at_eof() {
+        let html = "&#x41".to_string();
+        let mut tokenizer = HtmlTokenizer::new(html);
+        let expected = [
+            HtmlToken::Error(ParseError::new(
+                ParseErrorKind::MissingSemicolonAfterCharacterReference,
+                5,
+            )),
+            HtmlToken::Char('A'),
+        ];
+        for e in expected {
+            assert_eq!(Some(e), tokenizer.next());
+        }
+        assert!(tokenizer.next().is_none());
+    }
+
+    #[test]
+    fn test_eof_in_tag_reported_once() {
+        let mut tokenizer = HtmlTokenizer::new_streaming();
+        tokenizer.feed("<a hr");
+        tokenizer.end_of_input();
+        assert_eq!(
+            Some(HtmlToken::Error(ParseError::new(
+                ParseErrorKind::EofInTag,
+                5
+            ))),
+            tokenizer.next()
+        );
+        assert_eq!(None, tokenizer.next());
+    }
+
+    #[test]
+    fn test_html_to_text_basic() {
+        let html = "<p>Hello</p><script>var a=1;</script><div>World</div>";
+        assert_eq!("\nHello\n\nWorld\n", html_to_text(html));
+    }
+
+    #[test]
+    fn test_html_to_text_br_and_entities() {
+        let html = "line1<br>line2 &amp; more";
+        assert_eq!("line1\nline2 & more", html_to_text(html));
+    }
+
+    #[test]
+    fn test_serialize_round_trip_basic() {
+        let html = "<p class=\"a\">Hi &amp; bye</p>".to_string();
+        let tokenizer = HtmlTokenizer::new(html.clone());
+        assert_eq!(html, serialize(tokenizer));
+    }
+
+    #[test]
+    fn test_serialize_void_element_and_self_closing() {
+        let html = "<p>a<br>b</p><img src=\"x.png\" />".to_string();
+        let tokenizer = HtmlTokenizer::new(html.clone());
+        assert_eq!(html, serialize(tokenizer));
+    }
+
+    #[test]
+    fn test_serialize_comment_and_doctype() {
+        let html = "<!DOCTYPE html><!-- hi -->".to_string();
+        let tokenizer = HtmlTokenizer::new(html.clone());
+        assert_eq!(html, serialize(tokenizer));
+    }
 }